@@ -8,45 +8,50 @@ static GLOBAL: MiMalloc = MiMalloc;
 use libafl::observers::CanTrack;
 use libafl::HasMetadata;
 use libafl_bolts::{
-    current_nanos,
+    core_affinity::{CoreId, Cores},
+    current_nanos, current_time,
+    launcher::Launcher,
     os::dup2,
     rands::StdRand,
-    shmem::{ShMemProvider, StdShMemProvider},
+    shmem::{ShMem, ShMemProvider, StdShMemProvider},
     tuples::tuple_list,
-    AsSlice,
+    AsSlice, AsSliceMut, ClientId, Named,
 };
 
 use clap::{Arg, Command};
 use core::time::Duration;
 #[cfg(unix)]
-use nix::{self, unistd::dup};
-#[cfg(unix)]
-use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::io::AsRawFd;
 use std::{
     env,
-    fs::{self, File},
+    fs::{self, File, OpenOptions},
     io::{self, Write},
     path::PathBuf,
 };
 
 use libafl::{
-    corpus::{Corpus, InMemoryOnDiskCorpus, OnDiskCorpus},
-    events::SimpleRestartingEventManager,
-    executors::{inprocess::InProcessExecutor, ExitKind},
+    corpus::{Corpus, CorpusId, InMemoryOnDiskCorpus, OnDiskCorpus},
+    events::{EventConfig, EventFirer, LlmpRestartingEventManager},
+    executors::{forkserver::ForkserverExecutor, inprocess::InProcessExecutor, ExitKind},
     feedback_or,
-    feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback},
+    feedbacks::{CrashFeedback, Feedback, MaxMapFeedback, TimeFeedback},
     fuzzer::{Fuzzer, StdFuzzer},
     inputs::{BytesInput, HasTargetBytes, Input},
-    monitors::SimpleMonitor,
+    monitors::{format_duration_hms, ClientStats, Monitor},
     mutators::{
         havoc_mutations, scheduled::StdScheduledMutator, GrimoireExtensionMutator,
         GrimoireRandomDeleteMutator, GrimoireRecursiveReplacementMutator,
         GrimoireStringReplacementMutator, I2SRandReplace, Tokens,
     },
-    observers::{HitcountsMapObserver, TimeObserver},
-    schedulers::{IndexesLenTimeMinimizerScheduler, QueueScheduler},
+    observers::{
+        ConstMapObserver, HitcountsMapObserver, MapObserver, ObserversTuple, TimeObserver,
+    },
+    schedulers::{
+        IndexesLenTimeMinimizerScheduler, PowerQueueScheduler, PowerSchedule, QueueScheduler,
+        Scheduler,
+    },
     stages::{mutational::StdMutationalStage, GeneralizationStage, TracingStage},
-    state::{HasCorpus, StdState},
+    state::{HasCorpus, State, StdState},
     Error,
 };
 
@@ -57,6 +62,350 @@ use libafl_targets::{
 #[cfg(target_os = "linux")]
 use libafl_targets::autotokens;
 
+#[cfg(feature = "nyx")]
+use libafl::observers::StdMapObserver;
+#[cfg(feature = "nyx")]
+use libafl_nyx::{executor::NyxExecutorBuilder, helper::NyxHelper};
+
+/// The state type shared by every client spawned by the launcher.
+type ClientState =
+    StdState<BytesInput, InMemoryOnDiskCorpus<BytesInput>, StdRand, OnDiskCorpus<BytesInput>>;
+
+/// The llmp-backed, restarting event manager each client uses to talk to the broker.
+type ClientMgr = LlmpRestartingEventManager<ClientState, StdShMemProvider>;
+
+/// Settings for driving an external, AFL-instrumented target through a forkserver,
+/// for binaries that can't be linked into this process and re-entered in-process.
+#[derive(Debug, Clone)]
+struct ForkserverConfig {
+    /// Path to the AFL-instrumented target binary
+    path: PathBuf,
+    /// Arguments to pass to the target; `@@` is replaced with the input file path
+    args: Vec<String>,
+    /// Feed the input on stdin instead of via an `@@` argument
+    use_stdin: bool,
+}
+
+/// Which seed-selection strategy `--schedule` picked: plain FIFO, or one of the AFL-style
+/// power schedules, still wrapped by `IndexesLenTimeMinimizerScheduler`.
+#[derive(Debug, Clone, Copy)]
+enum ScheduleKind {
+    Queue,
+    Power(PowerSchedule),
+}
+
+fn parse_schedule(s: &str) -> ScheduleKind {
+    match s {
+        "queue" => ScheduleKind::Queue,
+        "explore" => ScheduleKind::Power(PowerSchedule::EXPLORE),
+        "fast" => ScheduleKind::Power(PowerSchedule::FAST),
+        "coe" => ScheduleKind::Power(PowerSchedule::COE),
+        "lin" => ScheduleKind::Power(PowerSchedule::LIN),
+        "quad" => ScheduleKind::Power(PowerSchedule::QUAD),
+        other => panic!(
+            "Unknown --schedule {other:?}; expected one of explore, fast, coe, lin, quad, queue"
+        ),
+    }
+}
+
+/// Either a plain FIFO [`QueueScheduler`] or an AFL-style [`PowerQueueScheduler`], so
+/// `--schedule` can pick the strategy at runtime without changing how the surrounding
+/// `IndexesLenTimeMinimizerScheduler` and edges observer are wired up.
+enum InnerScheduler<Q> {
+    Queue(QueueScheduler),
+    Power(Q),
+}
+
+impl<I, S, Q> Scheduler<I, S> for InnerScheduler<Q>
+where
+    QueueScheduler: Scheduler<I, S>,
+    Q: Scheduler<I, S>,
+{
+    fn on_add(&mut self, state: &mut S, idx: CorpusId) -> Result<(), Error> {
+        match self {
+            InnerScheduler::Queue(s) => s.on_add(state, idx),
+            InnerScheduler::Power(s) => s.on_add(state, idx),
+        }
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        match self {
+            InnerScheduler::Queue(s) => s.next(state),
+            InnerScheduler::Power(s) => s.next(state),
+        }
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        match self {
+            InnerScheduler::Queue(s) => s.on_evaluation(state, input, observers),
+            InnerScheduler::Power(s) => s.on_evaluation(state, input, observers),
+        }
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        match self {
+            InnerScheduler::Queue(s) => s.set_current_scheduled(state, next_id),
+            InnerScheduler::Power(s) => s.set_current_scheduled(state, next_id),
+        }
+    }
+}
+
+/// Builds the minimizer-wrapped scheduler for `schedule`, shared by every fuzzing backend
+/// so the three of them can't drift out of sync the way three copy-pasted `match`es would.
+fn build_scheduler<O>(
+    schedule: ScheduleKind,
+    state: &mut ClientState,
+    edges_observer: &O,
+) -> IndexesLenTimeMinimizerScheduler<InnerScheduler<PowerQueueScheduler<O, ClientState>>, O>
+where
+    O: MapObserver + Named,
+{
+    let inner_scheduler = match schedule {
+        ScheduleKind::Queue => InnerScheduler::Queue(QueueScheduler::new()),
+        ScheduleKind::Power(power_schedule) => InnerScheduler::Power(PowerQueueScheduler::new(
+            state,
+            edges_observer,
+            power_schedule,
+        )),
+    };
+    IndexesLenTimeMinimizerScheduler::new(edges_observer, inner_scheduler)
+}
+
+/// Mirrors a timed-out run's raw input into `hangs_dir`, as a side effect, so hangs are
+/// easy to tell apart from true crashes. Deliberately never reports the run as a
+/// "solution" itself (always returns `Ok(false)`): the fuzzer's one solutions corpus is
+/// reserved for [`CrashFeedback`], so timeouts land only in `hangs_dir` instead of also
+/// being duplicated into `crashes/`.
+#[derive(Debug)]
+struct TimeoutObjectiveFeedback {
+    hangs_dir: Option<PathBuf>,
+}
+
+impl TimeoutObjectiveFeedback {
+    fn new(hangs_dir: Option<PathBuf>) -> Self {
+        Self { hangs_dir }
+    }
+}
+
+impl Named for TimeoutObjectiveFeedback {
+    fn name(&self) -> &str {
+        "TimeoutObjectiveFeedback"
+    }
+}
+
+impl<S> Feedback<S> for TimeoutObjectiveFeedback
+where
+    S: State,
+    S::Input: HasTargetBytes,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &S::Input,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if *exit_kind != ExitKind::Timeout {
+            return Ok(false);
+        }
+        let Some(hangs_dir) = &self.hangs_dir else {
+            return Ok(false);
+        };
+        fs::create_dir_all(hangs_dir)?;
+        let name = format!("{:016x}", current_nanos());
+        fs::write(hangs_dir.join(name), input.target_bytes().as_slice())?;
+        // Not a "solution" in its own right: storage already happened above, and
+        // reporting `true` here would also add this input to the crash corpus.
+        Ok(false)
+    }
+}
+
+/// Builds the fuzzer's objective: a crash is always a solution (stored in the
+/// `crashes` directory by the caller's `OnDiskCorpus`); a timeout is additionally
+/// mirrored into `hangs_dir` unless `ignore_timeouts` is set, without itself becoming a
+/// "solution", so `crashes/` and `hangs/` stay disjoint. Shared by every fuzzing backend.
+fn build_objective(ignore_timeouts: bool, hangs_dir: PathBuf) -> impl Feedback<ClientState> {
+    feedback_or!(
+        CrashFeedback::new(),
+        TimeoutObjectiveFeedback::new(if ignore_timeouts {
+            None
+        } else {
+            Some(hangs_dir)
+        })
+    )
+}
+
+/// A [`Monitor`] that prints like [`MultiMonitor`](libafl::monitors::MultiMonitor), but also keeps an AFL++-compatible
+/// `fuzzer_stats` file and an append-only `plot_data` CSV up to date in `out_dir` on every
+/// update, so FuzzBench/AFL tooling that reads those files works against this harness too.
+struct AflStatsMonitor<F> {
+    print_fn: F,
+    start_time: Duration,
+    client_stats: Vec<ClientStats>,
+    out_dir: PathBuf,
+    last_find: Duration,
+    last_objective_count: u64,
+}
+
+impl<F> AflStatsMonitor<F>
+where
+    F: FnMut(String),
+{
+    fn new(out_dir: PathBuf, print_fn: F) -> Self {
+        Self {
+            print_fn,
+            start_time: current_time(),
+            client_stats: vec![],
+            out_dir,
+            last_find: current_time(),
+            last_objective_count: 0,
+        }
+    }
+
+    fn count_entries(dir: &PathBuf) -> u64 {
+        fs::read_dir(dir)
+            .map(|entries| entries.filter_map(Result::ok).count() as u64)
+            .unwrap_or(0)
+    }
+
+    // Every client explores independently between LLMP syncs, so no single client's
+    // local bitmap count is the campaign-wide figure; take the max across all of them
+    // (the true union is at least this large, since synced clients quickly learn of
+    // each other's finds) rather than an arbitrary first match.
+    fn edges_covered(&self) -> Option<u64> {
+        self.client_stats
+            .iter()
+            .filter_map(|client| {
+                client
+                    .user_monitor
+                    .iter()
+                    .find(|(name, _)| name.contains("edges"))
+                    .and_then(|(_, stats)| stats.to_string().parse::<u64>().ok())
+            })
+            .max()
+    }
+
+    fn write_stats(&mut self) {
+        let now = current_time();
+        let execs_done = self.total_execs();
+        let execs_per_sec = self.execs_per_sec_pretty();
+        let corpus_count = Self::count_entries(&self.out_dir.join("queue"));
+        // `crashes` and `hangs` are disjoint (a timeout is never also stored as a
+        // crash), so these counts don't overlap and can be safely summed below.
+        let saved_crashes = Self::count_entries(&self.out_dir.join("crashes"));
+        let saved_hangs = Self::count_entries(&self.out_dir.join("hangs"));
+        let edges_covered = self
+            .edges_covered()
+            .map(|n| n.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        if saved_crashes + saved_hangs != self.last_objective_count {
+            self.last_objective_count = saved_crashes + saved_hangs;
+            self.last_find = now;
+        }
+
+        if fs::create_dir_all(&self.out_dir).is_err() {
+            return;
+        }
+
+        let fuzzer_stats = format!(
+            "start_time     : {}\n\
+             last_update    : {}\n\
+             last_find      : {}\n\
+             execs_done     : {}\n\
+             execs_per_sec  : {}\n\
+             corpus_count   : {}\n\
+             saved_crashes  : {}\n\
+             saved_hangs    : {}\n\
+             edges_covered  : {}\n",
+            self.start_time.as_secs(),
+            now.as_secs(),
+            self.last_find.as_secs(),
+            execs_done,
+            execs_per_sec,
+            corpus_count,
+            saved_crashes,
+            saved_hangs,
+            edges_covered,
+        );
+        let _ = fs::write(self.out_dir.join("fuzzer_stats"), fuzzer_stats);
+
+        let plot_data_path = self.out_dir.join("plot_data");
+        let needs_header = !plot_data_path.exists();
+        if let Ok(mut file) = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&plot_data_path)
+        {
+            if needs_header {
+                let _ = writeln!(
+                    file,
+                    "# unix_time, execs_done, corpus_count, saved_crashes, saved_hangs, execs_per_sec"
+                );
+            }
+            let _ = writeln!(
+                file,
+                "{}, {}, {}, {}, {}, {}",
+                now.as_secs(),
+                execs_done,
+                corpus_count,
+                saved_crashes,
+                saved_hangs,
+                execs_per_sec,
+            );
+        }
+    }
+}
+
+impl<F> Monitor for AflStatsMonitor<F>
+where
+    F: FnMut(String),
+{
+    fn client_stats_mut(&mut self) -> &mut Vec<ClientStats> {
+        &mut self.client_stats
+    }
+
+    fn client_stats(&self) -> &[ClientStats] {
+        &self.client_stats
+    }
+
+    fn start_time(&self) -> Duration {
+        self.start_time
+    }
+
+    fn set_start_time(&mut self, time: Duration) {
+        self.start_time = time;
+    }
+
+    fn display(&mut self, event_msg: String, sender_id: ClientId) {
+        self.write_stats();
+        let line = format!(
+            "[#{}] [{}] run time: {}, clients: {}, corpus: {}, objectives: {}, executions: {}, exec/sec: {}",
+            sender_id.0,
+            event_msg,
+            format_duration_hms(&(current_time() - self.start_time)),
+            self.client_stats().len(),
+            self.corpus_size(),
+            self.objective_size(),
+            self.total_execs(),
+            self.execs_per_sec_pretty(),
+        );
+        (self.print_fn)(line);
+    }
+}
+
 /// The fuzzer main (as `no_mangle` C function)
 #[no_mangle]
 pub fn libafl_main() {
@@ -93,6 +442,53 @@ pub fn libafl_main() {
                 .long("input")
                 .help("The directory to read initial inputs from ('seeds')"),
         )
+        .arg(
+            Arg::new("cores")
+                .short('c')
+                .long("cores")
+                .help("Spawn a client on each of these cores, e.g. '0-7', '0,2,5' or 'all'")
+                .default_value("all"),
+        )
+        .arg(
+            Arg::new("broker-port")
+                .long("broker-port")
+                .help("The port the broker binds to, so that all clients can rendezvous on it")
+                .default_value("1337"),
+        )
+        .arg(
+            Arg::new("forkserver")
+                .long("forkserver")
+                .help("Path to an AFL-instrumented binary to drive through a forkserver, instead of fuzzing in-process"),
+        )
+        .arg(
+            Arg::new("forkserver-arg")
+                .long("arg")
+                .help("Argument to pass to the forkserver target; use '@@' as a placeholder for the input file path")
+                .action(clap::ArgAction::Append),
+        )
+        .arg(
+            Arg::new("forkserver-stdin")
+                .long("stdin")
+                .help("Feed the input to the forkserver target via stdin instead of '@@'")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("ignore-timeouts")
+                .long("ignore-timeouts")
+                .help("Don't save timed-out runs into 'hangs'; only crashes are kept, as before")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("nyx")
+                .long("nyx")
+                .help("Path to a Nyx VM sharedir to fuzz full snapshots instead of in-process (requires the `nyx` cargo feature)"),
+        )
+        .arg(
+            Arg::new("schedule")
+                .long("schedule")
+                .help("Power schedule to pick the next seed to mutate: explore, fast, coe, lin, quad, or queue for plain FIFO (default: queue, i.e. unchanged FIFO behavior)")
+                .default_value("queue"),
+        )
         .arg(Arg::new("remaining"))
         .try_get_matches()
     {
@@ -135,8 +531,11 @@ pub fn libafl_main() {
             return;
         }
     }
+    let out_dir_root = out_dir.clone();
     let mut crashes = out_dir.clone();
     crashes.push("crashes");
+    let mut hangs = out_dir.clone();
+    hangs.push("hangs");
     out_dir.push("queue");
 
     let in_dir = PathBuf::from(
@@ -159,7 +558,134 @@ pub fn libafl_main() {
             .expect("Could not parse timeout in milliseconds"),
     );
 
-    fuzz(in_dir, out_dir, crashes, tokens, timeout).expect("An error occurred while fuzzing");
+    let cores = Cores::from_cmdline(res.get_one::<String>("cores").unwrap())
+        .expect("Could not parse the --cores argument");
+
+    let broker_port: u16 = res
+        .get_one::<String>("broker-port")
+        .unwrap()
+        .parse()
+        .expect("Could not parse --broker-port");
+
+    // External orchestrators (e.g. the FuzzBench runner) start one job per core; the
+    // identifier names the event configuration, so jobs started with the same identifier
+    // on the same broker port are treated as running the same configuration.
+    let identifier = env::var("LIBAFL_IDENTIFIER").unwrap_or_else(|_| "default".to_string());
+
+    let forkserver = res
+        .get_one::<String>("forkserver")
+        .map(|path| ForkserverConfig {
+            path: PathBuf::from(path),
+            args: res
+                .get_many::<String>("forkserver-arg")
+                .map(|it| it.map(String::from).collect())
+                .unwrap_or_default(),
+            use_stdin: res.get_flag("forkserver-stdin"),
+        });
+
+    let ignore_timeouts = res.get_flag("ignore-timeouts");
+
+    let schedule = parse_schedule(res.get_one::<String>("schedule").unwrap());
+
+    let nyx_sharedir = res.get_one::<String>("nyx").map(PathBuf::from);
+    #[cfg(not(feature = "nyx"))]
+    if nyx_sharedir.is_some() {
+        println!("--nyx was given, but this binary was built without the `nyx` feature; rebuild with `--features nyx` to enable Nyx snapshot fuzzing.");
+        return;
+    }
+
+    // A handful of AFL env vars that external AFL-oriented tooling sets unconditionally;
+    // honor the ones that make sense for this harness.
+    if env::var("AFL_SKIP_CPUFREQ").is_ok() {
+        println!("AFL_SKIP_CPUFREQ set; not touching the CPU scaling governor");
+    }
+    let afl_no_ui = env::var("AFL_NO_UI").is_ok();
+    let afl_autoresume = env::var("AFL_AUTORESUME").is_ok();
+    let skip_initial_import = afl_autoresume
+        && fs::read_dir(&out_dir)
+            .map(|mut entries| entries.next().is_some())
+            .unwrap_or(false);
+    if skip_initial_import {
+        println!(
+            "AFL_AUTORESUME set and {:?} already has inputs; not re-importing {:?}",
+            &out_dir, &in_dir
+        );
+    }
+
+    let shmem_provider = StdShMemProvider::new().expect("Failed to init shared memory");
+    let monitor = AflStatsMonitor::new(out_dir_root, move |s| {
+        if !afl_no_ui {
+            println!("{s}");
+        }
+    });
+
+    let mut run_client = |state: Option<ClientState>, mgr: ClientMgr, core_id: CoreId| {
+        #[cfg(feature = "nyx")]
+        if let Some(sharedir) = nyx_sharedir.clone() {
+            return fuzz_nyx(
+                in_dir.clone(),
+                out_dir.clone(),
+                crashes.clone(),
+                hangs.clone(),
+                ignore_timeouts,
+                skip_initial_import,
+                schedule,
+                tokens.clone(),
+                timeout,
+                sharedir,
+                core_id.0 as usize,
+                state,
+                mgr,
+            );
+        }
+        #[cfg(not(feature = "nyx"))]
+        let _ = core_id;
+        if let Some(forkserver) = forkserver.clone() {
+            fuzz_forkserver(
+                in_dir.clone(),
+                out_dir.clone(),
+                crashes.clone(),
+                hangs.clone(),
+                ignore_timeouts,
+                skip_initial_import,
+                schedule,
+                tokens.clone(),
+                timeout,
+                forkserver,
+                state,
+                mgr,
+            )
+        } else {
+            fuzz(
+                in_dir.clone(),
+                out_dir.clone(),
+                crashes.clone(),
+                hangs.clone(),
+                ignore_timeouts,
+                skip_initial_import,
+                schedule,
+                tokens.clone(),
+                timeout,
+                state,
+                mgr,
+            )
+        }
+    };
+
+    match Launcher::builder()
+        .shmem_provider(shmem_provider)
+        .configuration(EventConfig::from_name(&identifier))
+        .monitor(monitor)
+        .run_client(&mut run_client)
+        .cores(&cores)
+        .broker_port(broker_port)
+        .build()
+        .launch()
+    {
+        Ok(()) => (),
+        Err(Error::ShuttingDown) => println!("Fuzzing stopped by user. Good bye."),
+        Err(err) => panic!("Failed to run launcher: {err:?}"),
+    }
 }
 
 fn run_testcases(filenames: &[&str]) {
@@ -191,48 +717,52 @@ fn run_testcases(filenames: &[&str]) {
     }
 }
 
-/// The actual fuzzer
+/// Builds the token-guided, havoc, and transforming Grimoire mutational stages shared by
+/// every fuzzing backend, as `(i2s_stage, havoc_stage, grimoire_stage)`. A macro rather
+/// than a function because `StdMutationalStage`'s mutator type isn't nameable (it's
+/// whatever `havoc_mutations()` expands to), but each call site infers it independently,
+/// so this still gives every backend one copy to keep in sync instead of three.
+macro_rules! build_mutational_stages {
+    () => {{
+        let i2s =
+            StdMutationalStage::new(StdScheduledMutator::new(tuple_list!(I2SRandReplace::new())));
+        let mutator = StdScheduledMutator::with_max_stack_pow(havoc_mutations(), 2);
+        let grimoire_mutator = StdScheduledMutator::with_max_stack_pow(
+            tuple_list!(
+                GrimoireExtensionMutator::new(),
+                GrimoireRecursiveReplacementMutator::new(),
+                GrimoireStringReplacementMutator::new(),
+                // give more probability to avoid large inputs
+                GrimoireRandomDeleteMutator::new(),
+                GrimoireRandomDeleteMutator::new(),
+            ),
+            3,
+        );
+        (
+            i2s,
+            StdMutationalStage::new(mutator),
+            StdMutationalStage::transforming(grimoire_mutator),
+        )
+    }};
+}
+
+/// The actual fuzzer, run once per core by the launcher
 fn fuzz(
     in_dir: PathBuf,
     corpus_dir: PathBuf,
     objective_dir: PathBuf,
+    hangs_dir: PathBuf,
+    ignore_timeouts: bool,
+    skip_initial_import: bool,
+    schedule: ScheduleKind,
     tokenfile: Option<PathBuf>,
     timeout: Duration,
+    state: Option<ClientState>,
+    mut mgr: ClientMgr,
 ) -> Result<(), Error> {
-    #[cfg(unix)]
-    let mut stdout_cpy = unsafe {
-        let new_fd = dup(io::stdout().as_raw_fd())?;
-        File::from_raw_fd(new_fd)
-    };
     #[cfg(unix)]
     let file_null = File::open("/dev/null")?;
 
-    // 'While the monitor are state, they are usually used in the broker - which is likely never restarted
-    let monitor = SimpleMonitor::new(|s| {
-        #[cfg(unix)]
-        writeln!(&mut stdout_cpy, "{}", s).unwrap();
-        #[cfg(windows)]
-        println!("{}", s);
-    });
-
-    // We need a shared map to store our state before a crash.
-    // This way, we are able to continue fuzzing afterwards.
-    let mut shmem_provider = StdShMemProvider::new()?;
-
-    let (state, mut mgr) = match SimpleRestartingEventManager::launch(monitor, &mut shmem_provider)
-    {
-        // The restarting state will spawn the same process again as child, then restarted it each time it crashes.
-        Ok(res) => res,
-        Err(err) => match err {
-            Error::ShuttingDown => {
-                return Ok(());
-            }
-            _ => {
-                panic!("Failed to setup the restarter: {}", err);
-            }
-        },
-    };
-
     let edges_observer = HitcountsMapObserver::new(unsafe { std_edges_map_observer("edges") })
         .track_indices()
         .track_novelties();
@@ -251,8 +781,12 @@ fn fuzz(
         TimeFeedback::new(&time_observer)
     );
 
-    // A feedback to choose if an input is a solution or not
-    let mut objective = CrashFeedback::new();
+    // A feedback to choose if an input is a solution or not. Crashes are the only
+    // "solution" the corpus knows about; timeouts are additionally mirrored into
+    // hangs_dir (unless --ignore-timeouts is set), since a hang is just as
+    // diagnostically interesting as a crash for targets like heartbleed, but they stay
+    // out of the crash corpus so crashes/ and hangs/ don't overlap.
+    let mut objective = build_objective(ignore_timeouts, hangs_dir);
 
     // If not restarting, create a State from scratch
     let mut state = state.unwrap_or_else(|| {
@@ -289,7 +823,7 @@ fn fuzz(
     }
 
     // A minimization+queue policy to get testcasess from the corpus
-    let scheduler = IndexesLenTimeMinimizerScheduler::new(&edges_observer, QueueScheduler::new());
+    let scheduler = build_scheduler(schedule, &mut state, &edges_observer);
 
     // A fuzzer with feedbacks and a corpus scheduler
     let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
@@ -298,21 +832,6 @@ fn fuzz(
 
     // The wrapped harness function, calling out to the LLVM-style harness
     let mut harness = |input: &BytesInput| {
-        /*use libafl::inputs::generalized::GeneralizedItem;
-        if input.grimoire_mutated {
-            if let Some(gen) = input.generalized() {
-                print!(">> ");
-                for e in gen {
-                    match e {
-                        GeneralizedItem::Bytes(b) => print!("`{}`", unsafe { std::str::from_utf8_unchecked(&b) }),
-                        GeneralizedItem::Gap => print!(" <GAP> "),
-                    }
-                }
-                print!("\n");
-            }
-            let bytes = input.generalized_to_bytes();
-            println!("@@ {}", unsafe { std::str::from_utf8_unchecked(&bytes) });
-        }*/
         let target_bytes = input.target_bytes();
         let bytes = target_bytes.as_slice();
         libfuzzer_test_one_input(&bytes);
@@ -354,8 +873,9 @@ fn fuzz(
         println!("Warning: LLVMFuzzerInitialize failed with -1")
     }
 
-    // In case the corpus is empty (on first run), reset
-    if state.corpus().count() < 1 {
+    // In case the corpus is empty (on first run), reset; AFL_AUTORESUME lets us skip this
+    // when the on-disk queue from a previous run is already populated.
+    if state.corpus().count() < 1 && !skip_initial_import {
         state
             .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &[in_dir.clone()])
             .unwrap_or_else(|_| {
@@ -365,29 +885,9 @@ fn fuzz(
         println!("We imported {} inputs from disk.", state.corpus().count());
     }
 
-    let i2s = StdMutationalStage::new(StdScheduledMutator::new(tuple_list!(I2SRandReplace::new())));
+    let (i2s, mutator_stage, grimoire_stage) = build_mutational_stages!();
 
-    // Setup a mutational stage with a basic bytes mutator
-    let mutator = StdScheduledMutator::with_max_stack_pow(havoc_mutations(), 2);
-    let grimoire_mutator = StdScheduledMutator::with_max_stack_pow(
-        tuple_list!(
-            GrimoireExtensionMutator::new(),
-            GrimoireRecursiveReplacementMutator::new(),
-            GrimoireStringReplacementMutator::new(),
-            // give more probability to avoid large inputs
-            GrimoireRandomDeleteMutator::new(),
-            GrimoireRandomDeleteMutator::new(),
-        ),
-        3,
-    );
-
-    let mut stages = tuple_list!(
-        generalization,
-        tracing,
-        i2s,
-        StdMutationalStage::new(mutator),
-        StdMutationalStage::transforming(grimoire_mutator)
-    );
+    let mut stages = tuple_list!(generalization, tracing, i2s, mutator_stage, grimoire_stage);
 
     // Remove target ouput (logs still survive)
     #[cfg(unix)]
@@ -400,3 +900,214 @@ fn fuzz(
     fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)?;
     Ok(())
 }
+
+/// Same fuzzing pipeline as [`fuzz`], but driving an external, AFL-instrumented target
+/// through a forkserver instead of calling `libfuzzer_test_one_input` in-process. Useful
+/// for stateful targets that need a fresh process per run. Runs only the i2s, havoc and
+/// Grimoire stages; the generalization and cmplog tracing stages of [`fuzz`] are not run.
+fn fuzz_forkserver(
+    in_dir: PathBuf,
+    corpus_dir: PathBuf,
+    objective_dir: PathBuf,
+    hangs_dir: PathBuf,
+    ignore_timeouts: bool,
+    skip_initial_import: bool,
+    schedule: ScheduleKind,
+    tokenfile: Option<PathBuf>,
+    timeout: Duration,
+    forkserver: ForkserverConfig,
+    state: Option<ClientState>,
+    mut mgr: ClientMgr,
+) -> Result<(), Error> {
+    const MAP_SIZE: usize = 65536;
+
+    let mut shmem_provider = StdShMemProvider::new()?;
+    let mut shmem = shmem_provider.new_shmem(MAP_SIZE)?;
+    shmem.write_to_env("__AFL_SHM_ID")?;
+    let shmem_buf = shmem.as_slice_mut();
+
+    // The coverage map is written by the target via the shared memory segment above,
+    // instead of the `std_edges_map_observer` used by the in-process instrumentation.
+    let edges_observer = HitcountsMapObserver::new(ConstMapObserver::<_, MAP_SIZE>::new(
+        "shared_mem",
+        shmem_buf,
+    ))
+    .track_indices()
+    .track_novelties();
+
+    let time_observer = TimeObserver::new("time");
+
+    let mut feedback = feedback_or!(
+        MaxMapFeedback::new(&edges_observer),
+        TimeFeedback::new(&time_observer)
+    );
+
+    let mut objective = build_objective(ignore_timeouts, hangs_dir);
+
+    let mut state = state.unwrap_or_else(|| {
+        StdState::new(
+            StdRand::with_seed(current_nanos()),
+            InMemoryOnDiskCorpus::new(corpus_dir).unwrap(),
+            OnDiskCorpus::new(objective_dir).unwrap(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap()
+    });
+
+    let mut tokens = Tokens::default();
+    if let Some(tokenfile) = &tokenfile {
+        tokens.add_from_file(tokenfile)?;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        tokens += autotokens()?;
+    }
+
+    let scheduler = build_scheduler(schedule, &mut state, &edges_observer);
+    let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+    // `@@` in the configured args is replaced with the input file path by
+    // `parse_afl_cmdline`; with `--stdin` we drop any `@@` and let the forkserver fall
+    // back to feeding the testcase on the target's stdin instead.
+    let target_args = if forkserver.use_stdin {
+        forkserver
+            .args
+            .into_iter()
+            .filter(|arg| arg != "@@")
+            .collect()
+    } else {
+        forkserver.args
+    };
+
+    let mut executor = ForkserverExecutor::builder()
+        .program(forkserver.path)
+        .shmem_provider(&mut shmem_provider)
+        .parse_afl_cmdline(target_args)
+        .is_persistent(false)
+        .timeout(timeout)
+        .autotokens(&mut tokens)
+        .build(tuple_list!(edges_observer, time_observer))?;
+
+    if state.metadata_map().get::<Tokens>().is_none() && !tokens.is_empty() {
+        state.add_metadata(tokens);
+    }
+
+    // The actual target run starts here.
+    // In case the corpus is empty (on first run), reset; AFL_AUTORESUME lets us skip this
+    // when the on-disk queue from a previous run is already populated.
+    if state.corpus().count() < 1 && !skip_initial_import {
+        state
+            .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &[in_dir.clone()])
+            .unwrap_or_else(|_| {
+                println!("Failed to load initial corpus at {:?}", &in_dir);
+                std::process::exit(0);
+            });
+        println!("We imported {} inputs from disk.", state.corpus().count());
+    }
+
+    let (i2s, mutator_stage, grimoire_stage) = build_mutational_stages!();
+
+    let mut stages = tuple_list!(i2s, mutator_stage, grimoire_stage);
+
+    fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)?;
+    Ok(())
+}
+
+/// Same fuzzing pipeline as [`fuzz`], but driving a full VM snapshot through Nyx instead
+/// of calling `libfuzzer_test_one_input` in-process. Lets the same corpus/scheduler/
+/// mutator stack fuzz kernels, network stacks, and other targets that can't be reset
+/// in-process at all. Runs only the i2s, havoc and Grimoire stages; the generalization
+/// and cmplog tracing stages of [`fuzz`] are not run. Gated behind the `nyx` cargo
+/// feature; the default libpng build never links `libafl_nyx`.
+#[cfg(feature = "nyx")]
+fn fuzz_nyx(
+    in_dir: PathBuf,
+    corpus_dir: PathBuf,
+    objective_dir: PathBuf,
+    hangs_dir: PathBuf,
+    ignore_timeouts: bool,
+    skip_initial_import: bool,
+    schedule: ScheduleKind,
+    tokenfile: Option<PathBuf>,
+    timeout: Duration,
+    sharedir: PathBuf,
+    core_id: usize,
+    state: Option<ClientState>,
+    mut mgr: ClientMgr,
+) -> Result<(), Error> {
+    // Each client needs its own Nyx VM instance/workdir; reusing cpu_id 0 for every
+    // client would make them all fight over the same snapshot instance.
+    let nyx_helper = NyxHelper::new(sharedir, core_id, false, false, None)?;
+
+    // The Nyx executor exposes the guest's coverage bitmap as a plain byte slice; wire it
+    // into the same `MaxMapFeedback` / `IndexesLenTimeMinimizerScheduler` pair the
+    // in-process and forkserver backends use.
+    let edges_observer = HitcountsMapObserver::new(unsafe {
+        StdMapObserver::from_mut_ptr(
+            "edges",
+            nyx_helper.trace_bits.as_ptr() as *mut u8,
+            nyx_helper.trace_bits.len(),
+        )
+    })
+    .track_indices()
+    .track_novelties();
+
+    let time_observer = TimeObserver::new("time");
+
+    let mut feedback = feedback_or!(
+        MaxMapFeedback::new(&edges_observer),
+        TimeFeedback::new(&time_observer)
+    );
+
+    let mut objective = build_objective(ignore_timeouts, hangs_dir);
+
+    let mut state = state.unwrap_or_else(|| {
+        StdState::new(
+            StdRand::with_seed(current_nanos()),
+            InMemoryOnDiskCorpus::new(corpus_dir).unwrap(),
+            OnDiskCorpus::new(objective_dir).unwrap(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap()
+    });
+
+    if state.metadata_map().get::<Tokens>().is_none() {
+        let mut toks = Tokens::default();
+        if let Some(tokenfile) = &tokenfile {
+            toks.add_from_file(tokenfile)?;
+        }
+        #[cfg(target_os = "linux")]
+        {
+            toks += autotokens()?;
+        }
+        if !toks.is_empty() {
+            state.add_metadata(toks);
+        }
+    }
+
+    let scheduler = build_scheduler(schedule, &mut state, &edges_observer);
+    let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+    let mut executor = NyxExecutorBuilder::new(&nyx_helper)
+        .timeout(timeout)
+        .build(tuple_list!(edges_observer, time_observer));
+
+    if state.corpus().count() < 1 && !skip_initial_import {
+        state
+            .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &[in_dir.clone()])
+            .unwrap_or_else(|_| {
+                println!("Failed to load initial corpus at {:?}", &in_dir);
+                std::process::exit(0);
+            });
+        println!("We imported {} inputs from disk.", state.corpus().count());
+    }
+
+    let (i2s, mutator_stage, grimoire_stage) = build_mutational_stages!();
+
+    let mut stages = tuple_list!(i2s, mutator_stage, grimoire_stage);
+
+    fuzzer.fuzz_loop(&mut stages, &mut executor, &mut state, &mut mgr)?;
+    Ok(())
+}